@@ -0,0 +1,497 @@
+//! Coin selection for direct sends.
+//!
+//! Given a target amount, a fee rate, and a set of candidate UTXOs, [`select_coins`] picks a
+//! minimal subset of inputs to spend instead of naively spending every UTXO the caller hands in.
+//!
+//! The primary strategy is Branch-and-Bound (BnB): candidates are ranked by *effective value*
+//! (the amount left over after paying for that input's own weight at `fee_rate`), and a DFS over
+//! include/exclude branches looks for a subset whose effective value lands in
+//! `[target, target + cost_of_change]` — a changeless match. Branches are pruned as soon as the
+//! remaining candidates can't possibly reach `target`.
+//!
+//! If BnB can't find a changeless match within a bounded number of tries, selection falls back to
+//! a single-pass heuristic that accepts a change output and picks whichever of a couple of simple
+//! orderings minimizes the *waste* metric: `waste = Σ input_weight * (fee_rate - long_term_fee_rate)
+//! + (cost_of_change if change else excess)`.
+
+use bitcoin::{Amount, ScriptBuf};
+use bitcoind::bitcoincore_rpc::json::ListUnspentResultEntry;
+
+use super::direct_send::{P2PWPKH_WITNESS_SIZE, P2WSH_MULTISIG_2OF2_WITNESS_SIZE};
+use crate::wallet::UTXOSpendInfo;
+
+/// Base (non-witness) size in vbytes contributed by a single input: 36-byte outpoint,
+/// 4-byte sequence, and a 1-byte empty `scriptSig` length.
+const INPUT_BASE_VBYTES: f64 = 41.0;
+
+/// Size in vbytes of a fresh P2WPKH change output: 8-byte value, 1-byte script length,
+/// 22-byte script.
+const CHANGE_OUTPUT_VBYTES: f64 = 31.0;
+
+/// Dust threshold assumed for a P2WPKH change output.
+const CHANGE_DUST_SATS: i64 = 294;
+
+/// Conservative future feerate (sat/vB) used only for the waste metric, approximating the
+/// minimum relay fee a change output will eventually cost to spend.
+const LONG_TERM_FEE_RATE: f64 = 1.0;
+
+/// Upper bound on the number of DFS nodes Branch-and-Bound will explore before giving up and
+/// falling back to the change-accepting heuristic.
+const BNB_MAX_TRIES: usize = 100_000;
+
+/// Vbytes contributed by the parts of a transaction that aren't inputs or a change output:
+/// 4-byte version, 4-byte locktime, and the input/output count varints (1 byte each for the
+/// transaction sizes this wallet builds).
+const TX_OVERHEAD_VBYTES: f64 = 10.0;
+
+/// Vbytes a single output of `script_pubkey` contributes: 8-byte value, its scriptPubKey length
+/// varint, and the script itself.
+fn output_vsize(script_pubkey: &ScriptBuf) -> f64 {
+    (8 + 1 + script_pubkey.len()) as f64
+}
+
+/// Fee, at `fee_rate`, for everything in the transaction besides the inputs: the skeleton plus
+/// every recipient output in `output_scripts`. Mirrors Bitcoin Core's `not_input_fees` —
+/// `select_coins`'s target only has to be covered by candidates' `effective_value`, which already
+/// nets off each input's own spend cost, so the skeleton and recipient outputs have to be folded
+/// into the target separately or BnB will happily accept a changeless match that leaves them
+/// unfunded.
+pub(super) fn non_input_fee(output_scripts: &[ScriptBuf], fee_rate: f64) -> Amount {
+    let vsize =
+        TX_OVERHEAD_VBYTES + output_scripts.iter().map(|s| output_vsize(s)).sum::<f64>();
+    Amount::from_sat((vsize * fee_rate).ceil() as u64)
+}
+
+/// vbytes an input of this `spend_info` kind contributes to a transaction, including the
+/// witness discount.
+fn input_vsize(spend_info: &UTXOSpendInfo) -> f64 {
+    let witness_size = match spend_info {
+        UTXOSpendInfo::SeedCoin { .. } => P2PWPKH_WITNESS_SIZE,
+        UTXOSpendInfo::SwapCoin { .. } => P2WSH_MULTISIG_2OF2_WITNESS_SIZE,
+        UTXOSpendInfo::FidelityBondCoin { .. }
+        | UTXOSpendInfo::HashlockContract { .. }
+        | UTXOSpendInfo::TimelockContract { .. } => 0,
+    };
+    INPUT_BASE_VBYTES + (witness_size as f64) / 4.0
+}
+
+/// `utxo.amount - input_weight * fee_rate`, i.e. what the UTXO contributes net of the fee
+/// needed to spend it.
+fn effective_value(amount: Amount, spend_info: &UTXOSpendInfo, fee_rate: f64) -> i64 {
+    let input_fee = (input_vsize(spend_info) * fee_rate).ceil() as i64;
+    amount.to_sat() as i64 - input_fee
+}
+
+/// Extra cost of using a change output: creating it now, plus spending it later at
+/// `long_term_fee_rate`.
+fn cost_of_change(fee_rate: f64) -> i64 {
+    let change_spend_vsize = INPUT_BASE_VBYTES + (P2PWPKH_WITNESS_SIZE as f64) / 4.0;
+    let creation_cost = (CHANGE_OUTPUT_VBYTES * fee_rate).ceil() as i64;
+    let spending_cost = (change_spend_vsize * LONG_TERM_FEE_RATE).ceil() as i64;
+    CHANGE_DUST_SATS.max(creation_cost + spending_cost)
+}
+
+/// DFS over include/exclude branches of `pool` (sorted by effective value, descending),
+/// looking for a subset summing into `[target, target + cost_of_change]`.
+///
+/// `remaining` is the suffix sum of `pool`, i.e. `remaining[i] == pool[i..].iter().sum()`;
+/// it lets a branch be pruned the moment even taking every UTXO left can't reach `target`.
+fn bnb_search(
+    pool: &[i64],
+    remaining: &[i64],
+    index: usize,
+    current_value: i64,
+    current_selection: &mut Vec<usize>,
+    target: i64,
+    cost_of_change: i64,
+    tries: &mut usize,
+    best: &mut Option<(Vec<usize>, i64)>,
+) {
+    *tries += 1;
+    if *tries > BNB_MAX_TRIES {
+        return;
+    }
+
+    if current_value >= target {
+        let waste = current_value - target;
+        if waste <= cost_of_change
+            && best.as_ref().map_or(true, |(_, best_waste)| waste < *best_waste)
+        {
+            *best = Some((current_selection.clone(), waste));
+        }
+        // Candidates are sorted by effective value descending, but further inclusions can
+        // only move further away from target once we've already met it.
+        return;
+    }
+
+    if index == pool.len() || current_value + remaining[index] < target {
+        return;
+    }
+
+    current_selection.push(index);
+    bnb_search(
+        pool,
+        remaining,
+        index + 1,
+        current_value + pool[index],
+        current_selection,
+        target,
+        cost_of_change,
+        tries,
+        best,
+    );
+    current_selection.pop();
+
+    bnb_search(
+        pool,
+        remaining,
+        index + 1,
+        current_value,
+        current_selection,
+        target,
+        cost_of_change,
+        tries,
+        best,
+    );
+}
+
+/// Accumulate `order` (a permutation of `0..candidates.len()`) until the running effective
+/// value covers `target`, returning the selected indices (in `order`'s iteration order) and
+/// their waste, assuming a change output is added.
+fn accumulate_with_change(
+    candidates: &[(i64, f64)],
+    order: &[usize],
+    target: i64,
+    fee_rate: f64,
+) -> Option<(Vec<usize>, i64)> {
+    let mut selected = Vec::new();
+    let mut sum_effective = 0i64;
+    let mut sum_weighted_fee = 0f64;
+    for &idx in order {
+        let (effective, vsize) = candidates[idx];
+        selected.push(idx);
+        sum_effective += effective;
+        sum_weighted_fee += vsize * (fee_rate - LONG_TERM_FEE_RATE);
+        if sum_effective >= target {
+            let waste = sum_weighted_fee.round() as i64 + cost_of_change(fee_rate);
+            return Some((selected, waste));
+        }
+    }
+    None
+}
+
+/// Single-pass fallback used when BnB can't find a changeless match: try a couple of simple
+/// orderings and keep whichever produces the lower waste.
+fn select_with_change(candidates: &[(i64, f64)], target: i64, fee_rate: f64) -> Option<Vec<usize>> {
+    let mut by_value_desc: Vec<usize> = (0..candidates.len()).collect();
+    by_value_desc.sort_by_key(|&i| std::cmp::Reverse(candidates[i].0));
+    let mut by_value_asc = by_value_desc.clone();
+    by_value_asc.reverse();
+
+    [by_value_desc, by_value_asc]
+        .into_iter()
+        .filter_map(|order| accumulate_with_change(candidates, &order, target, fee_rate))
+        .min_by_key(|(_, waste)| *waste)
+        .map(|(selected, _)| selected)
+}
+
+/// Pick a minimal subset of `candidates` to cover `target` at `fee_rate`.
+///
+/// Tries Branch-and-Bound for a changeless match first, falling back to a waste-minimizing
+/// selection that accepts a change output. If neither can cover `target` (the candidates don't
+/// hold enough value), every candidate is returned so the caller's own insufficient-funds check
+/// can report the shortfall.
+pub(super) fn select_coins<'a>(
+    candidates: &[(&'a ListUnspentResultEntry, &'a UTXOSpendInfo)],
+    target: Amount,
+    fee_rate: f64,
+) -> Vec<(&'a ListUnspentResultEntry, &'a UTXOSpendInfo)> {
+    let target = target.to_sat() as i64;
+
+    let effective_values: Vec<i64> = candidates
+        .iter()
+        .map(|(utxo, info)| effective_value(utxo.amount, info, fee_rate))
+        .collect();
+    // Candidates with a non-positive effective value can never help reach `target`; they're left
+    // out so that the suffix sums below are a true upper bound on what's still reachable.
+    let mut by_effective_value: Vec<usize> = (0..candidates.len())
+        .filter(|&i| effective_values[i] > 0)
+        .collect();
+    by_effective_value.sort_by_key(|&i| std::cmp::Reverse(effective_values[i]));
+
+    let pool: Vec<i64> = by_effective_value.iter().map(|&i| effective_values[i]).collect();
+    let mut remaining = vec![0i64; pool.len() + 1];
+    for i in (0..pool.len()).rev() {
+        remaining[i] = remaining[i + 1] + pool[i];
+    }
+
+    if remaining[0] < target {
+        // Not enough value even using everything; let spend_coins' own check surface the error.
+        return candidates.to_vec();
+    }
+
+    let mut best = None;
+    let mut tries = 0;
+    bnb_search(
+        &pool,
+        &remaining,
+        0,
+        0,
+        &mut Vec::new(),
+        target,
+        cost_of_change(fee_rate),
+        &mut tries,
+        &mut best,
+    );
+
+    let chosen = if let Some((selection, _)) = best {
+        selection
+    } else {
+        let weighted: Vec<(i64, f64)> = by_effective_value
+            .iter()
+            .map(|&i| (effective_values[i], input_vsize(candidates[i].1)))
+            .collect();
+        select_with_change(&weighted, target, fee_rate).unwrap_or_else(|| (0..pool.len()).collect())
+    };
+
+    chosen
+        .into_iter()
+        .map(|i| candidates[by_effective_value[i]])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use bitcoin::{ScriptBuf, Txid};
+
+    use super::*;
+
+    fn dummy_entry(amount_sats: u64) -> ListUnspentResultEntry {
+        ListUnspentResultEntry {
+            txid: Txid::from_str(&"00".repeat(32)).unwrap(),
+            vout: 0,
+            address: None,
+            label: None,
+            redeem_script: None,
+            witness_script: None,
+            script_pub_key: ScriptBuf::new(),
+            amount: Amount::from_sat(amount_sats),
+            confirmations: 1,
+            spendable: true,
+            solvable: true,
+            descriptor: None,
+            safe: true,
+        }
+    }
+
+    fn seed_coin() -> UTXOSpendInfo {
+        UTXOSpendInfo::SeedCoin {
+            path: "m/0/0".to_string(),
+            input_value: 0,
+        }
+    }
+
+    #[test]
+    fn effective_value_subtracts_input_fee() {
+        let seed = seed_coin();
+        let swap = UTXOSpendInfo::SwapCoin {
+            multisig_redeemscript: ScriptBuf::new(),
+        };
+
+        let amount = Amount::from_sat(100_000);
+        let seed_value = effective_value(amount, &seed, 10.0);
+        let swap_value = effective_value(amount, &swap, 10.0);
+
+        // A SwapCoin's 2-of-2 witness is heavier than a SeedCoin's P2WPKH one, so it costs
+        // more to spend at the same feerate and its effective value should be strictly lower.
+        assert!(swap_value < seed_value);
+        assert!(seed_value < amount.to_sat() as i64);
+    }
+
+    #[test]
+    fn effective_value_can_go_negative_for_dust_at_high_feerate() {
+        let dust = Amount::from_sat(200);
+        let value = effective_value(dust, &seed_coin(), 200.0);
+        assert!(value < 0);
+    }
+
+    #[test]
+    fn cost_of_change_has_a_dust_floor() {
+        // At a near-zero feerate, creating the change output costs 1 sat and spending it later at
+        // LONG_TERM_FEE_RATE costs 68, which together stay below the dust threshold, so the floor
+        // is the dust threshold itself.
+        assert_eq!(cost_of_change(0.001), CHANGE_DUST_SATS);
+    }
+
+    #[test]
+    fn bnb_search_finds_an_exact_changeless_match() {
+        let pool = vec![50_000, 30_000, 20_000];
+        let remaining = {
+            let mut r = vec![0i64; pool.len() + 1];
+            for i in (0..pool.len()).rev() {
+                r[i] = r[i + 1] + pool[i];
+            }
+            r
+        };
+        let mut best = None;
+        let mut tries = 0;
+        bnb_search(
+            &pool,
+            &remaining,
+            0,
+            0,
+            &mut Vec::new(),
+            80_000,
+            0,
+            &mut tries,
+            &mut best,
+        );
+
+        let (selection, waste) = best.expect("an exact subset summing to the target exists");
+        let sum: i64 = selection.iter().map(|&i| pool[i]).sum();
+        assert_eq!(sum, 80_000);
+        assert_eq!(waste, 0);
+    }
+
+    #[test]
+    fn bnb_search_respects_the_cost_of_change_bound() {
+        // No subset sums into [target, target + cost_of_change], so BnB should report no match
+        // rather than accepting a too-wasteful one.
+        let pool = vec![50_000, 30_000];
+        let remaining = vec![80_000, 30_000, 0];
+        let mut best = None;
+        let mut tries = 0;
+        bnb_search(
+            &pool,
+            &remaining,
+            0,
+            0,
+            &mut Vec::new(),
+            79_000,
+            500,
+            &mut tries,
+            &mut best,
+        );
+        assert!(best.is_none());
+    }
+
+    #[test]
+    fn accumulate_with_change_stops_as_soon_as_target_is_covered() {
+        // (effective_value, vsize) pairs.
+        let candidates = vec![(30_000, 68.0), (30_000, 68.0), (30_000, 68.0)];
+        let order = vec![0, 1, 2];
+
+        let (selected, waste) =
+            accumulate_with_change(&candidates, &order, 50_000, 10.0).expect("covers the target");
+
+        // Only the first two are needed to reach 50_000; the third should be left unselected.
+        assert_eq!(selected, vec![0, 1]);
+        assert!(waste > 0);
+    }
+
+    #[test]
+    fn accumulate_with_change_returns_none_when_insufficient() {
+        let candidates = vec![(10_000, 68.0), (10_000, 68.0)];
+        let order = vec![0, 1];
+        assert!(accumulate_with_change(&candidates, &order, 50_000, 10.0).is_none());
+    }
+
+    #[test]
+    fn select_with_change_picks_the_lower_waste_ordering() {
+        let candidates = vec![(60_000, 68.0), (25_000, 68.0), (25_000, 68.0)];
+        let selected =
+            select_with_change(&candidates, 50_000, 10.0).expect("some ordering covers the target");
+        let sum: i64 = selected.iter().map(|&i| candidates[i].0).sum();
+        assert!(sum >= 50_000);
+        // Descending order covers the target with the single 60,000 input (waste 612 + 378);
+        // ascending needs both 25,000 inputs (waste 1,224 + 378), so descending wins.
+        assert_eq!(selected, vec![0]);
+    }
+
+    #[test]
+    fn select_coins_finds_a_changeless_subset_without_spending_everything() {
+        // The 1,000,000-sat UTXO is far more than enough on its own but would leave a huge,
+        // wasteful "change" if selected; the other two have effective values (amount minus a
+        // 68-sat input fee at 1 sat/vB) of 49,932 and 29,932, summing to exactly the target, so
+        // BnB should prefer that changeless pair and leave the large UTXO unspent.
+        let utxos = vec![
+            dummy_entry(50_000),
+            dummy_entry(30_000),
+            dummy_entry(1_000_000),
+        ];
+        let infos = vec![seed_coin(), seed_coin(), seed_coin()];
+        let candidates: Vec<_> = utxos.iter().zip(infos.iter()).collect();
+
+        let selected = select_coins(&candidates, Amount::from_sat(79_864), 1.0);
+
+        let total: u64 = selected.iter().map(|(utxo, _)| utxo.amount.to_sat()).sum();
+        assert_eq!(total, 80_000);
+        assert_eq!(selected.len(), 2);
+    }
+
+    #[test]
+    fn non_input_fee_covers_skeleton_and_every_recipient_output() {
+        let script = ScriptBuf::from(vec![0u8; 22]); // P2WPKH-sized output
+        let fee = non_input_fee(&[script.clone(), script], 10.0);
+        // (TX_OVERHEAD_VBYTES + 2 * (8 + 1 + 22)) * 10 = (10 + 62) * 10 = 720
+        assert_eq!(fee, Amount::from_sat(720));
+    }
+
+    #[test]
+    fn select_coins_target_must_include_non_input_fee_or_it_under_selects() {
+        // A single recipient paying to a 22-byte (P2WPKH-sized) script at fee_rate = 10 sat/vB
+        // needs extra sats for the skeleton and that output that `effective_value` never accounts
+        // for (it only nets off each input's own spend cost). Handing `select_coins` just
+        // `fixed_total` as its target can return a changeless match with too little value; folding
+        // `non_input_fee` into the target forces it to pick enough to cover them too.
+        let fee_rate = 10.0;
+        let fixed_total = 100_000i64;
+        let fee = non_input_fee(&[ScriptBuf::from(vec![0u8; 22])], fee_rate).to_sat() as i64;
+
+        // Chosen so its effective value lands exactly on `fixed_total` — an exact changeless
+        // match if the target were `fixed_total` alone, but short of `fixed_total + fee` once a
+        // real transaction (skeleton + recipient output) is actually built around it.
+        let input_fee = (input_vsize(&seed_coin()) * fee_rate).ceil() as i64;
+        let exact_match_amount = fixed_total + input_fee;
+        let utxos = vec![dummy_entry(exact_match_amount as u64), dummy_entry(2_000)];
+        let infos = vec![seed_coin(), seed_coin()];
+        let candidates: Vec<_> = utxos.iter().zip(infos.iter()).collect();
+
+        let uncorrected = select_coins(&candidates, Amount::from_sat(fixed_total as u64), fee_rate);
+        let uncorrected_total: i64 = uncorrected
+            .iter()
+            .map(|(utxo, _)| utxo.amount.to_sat() as i64)
+            .sum();
+        assert_eq!(
+            uncorrected_total, exact_match_amount,
+            "without the fix, only the exact match is picked, leaving no room for the skeleton/output fee"
+        );
+
+        let corrected = select_coins(
+            &candidates,
+            Amount::from_sat((fixed_total + fee) as u64),
+            fee_rate,
+        );
+        let corrected_total: i64 = corrected
+            .iter()
+            .map(|(utxo, _)| utxo.amount.to_sat() as i64)
+            .sum();
+        assert!(
+            corrected_total >= fixed_total + fee,
+            "target that folds in non_input_fee must select enough to cover it"
+        );
+    }
+
+    #[test]
+    fn select_coins_returns_everything_when_funds_are_insufficient() {
+        let utxos = vec![dummy_entry(10_000), dummy_entry(5_000)];
+        let infos = vec![seed_coin(), seed_coin()];
+        let candidates: Vec<_> = utxos.iter().zip(infos.iter()).collect();
+
+        let selected = select_coins(&candidates, Amount::from_sat(1_000_000), 1.0);
+
+        assert_eq!(selected.len(), candidates.len());
+    }
+}
@@ -0,0 +1,64 @@
+//! Blocking helper for waiting on an incoming deposit.
+//!
+//! Swap bootstrap needs coins sitting in the wallet before it can hand them to
+//! [`super::direct_send::Wallet::spend_coins`]; [`Wallet::wait_for_deposit`] is the "fund me then
+//! continue" primitive for that: it hands out a fresh external address and blocks, resyncing the
+//! wallet at a fixed interval, until that address holds enough confirmed value.
+
+use std::{thread, time::Duration};
+
+use bitcoin::{Address, Amount};
+use bitcoind::bitcoincore_rpc::json::ListUnspentResultEntry;
+
+use super::{api::UTXOSpendInfo, error::WalletError, Wallet};
+
+impl Wallet {
+    /// Returns a fresh external deposit address and blocks until it's funded.
+    ///
+    /// Re-syncs the wallet every `poll_interval` and checks the address' UTXOs with at least
+    /// `min_confirmations` confirmations. Waits until their total value reaches `min_amount`, or,
+    /// if `min_amount` is `None`, until any confirmed coin lands there at all. Returns the
+    /// address and the UTXOs (with spend info) that satisfied the wait, ready to feed into the
+    /// coin-selection path.
+    pub fn wait_for_deposit(
+        &mut self,
+        min_amount: Option<Amount>,
+        min_confirmations: u32,
+        poll_interval: Duration,
+    ) -> Result<(Address, Vec<(ListUnspentResultEntry, UTXOSpendInfo)>), WalletError> {
+        let deposit_address = self.get_next_external_address()?;
+        log::info!("Waiting for deposit to {deposit_address}");
+
+        loop {
+            self.sync()?;
+
+            let deposited_utxos: Vec<(ListUnspentResultEntry, UTXOSpendInfo)> = self
+                .list_unspent_from_wallet(false, false)?
+                .into_iter()
+                .filter(|(utxo, _)| {
+                    utxo.address.as_ref().map(|a| a.assume_checked_ref()) == Some(&deposit_address)
+                        && utxo.confirmations >= min_confirmations
+                })
+                .collect();
+
+            let total_value = deposited_utxos
+                .iter()
+                .fold(Amount::ZERO, |acc, (utxo, _)| acc + utxo.amount);
+
+            let funded = match min_amount {
+                Some(min_amount) => total_value >= min_amount,
+                None => !deposited_utxos.is_empty(),
+            };
+
+            if funded {
+                log::info!("Deposit of {total_value} confirmed at {deposit_address}");
+                return Ok((deposit_address, deposited_utxos));
+            }
+
+            log::debug!(
+                "Still waiting for deposit to {deposit_address}: {total_value} confirmed so far"
+            );
+            thread::sleep(poll_interval);
+        }
+    }
+}
@@ -14,10 +14,19 @@ use bitcoind::bitcoincore_rpc::{json::ListUnspentResultEntry, RawTx, RpcApi};
 
 use crate::wallet::api::UTXOSpendInfo;
 
-use super::{error::WalletError, Wallet};
-
-const P2PWPKH_WITNESS_SIZE: usize = 107;
-const P2WSH_MULTISIG_2OF2_WITNESS_SIZE: usize = 222;
+use super::{coin_select, error::WalletError, Wallet};
+
+pub(super) const P2PWPKH_WITNESS_SIZE: usize = 107;
+pub(super) const P2WSH_MULTISIG_2OF2_WITNESS_SIZE: usize = 222;
+
+/// Converts a transaction's base size and total witness size (in bytes, i.e. weight units) into
+/// its vsize and fee at `fee_rate`, shared by every spend path (regular, Fidelity Bond, and
+/// contract) so they all round the same way.
+pub(super) fn estimate_fee(base_size: usize, witness_size: usize, fee_rate: f64) -> (usize, Amount) {
+    let vsize = (base_size * 4 + witness_size) / 4;
+    let fee = Amount::from_sat((fee_rate * vsize as f64).ceil() as u64);
+    (vsize, fee)
+}
 
 /// Represents options for specifying the amount to be sent in a transaction.
 #[derive(Debug, Clone, PartialEq)]
@@ -51,6 +60,15 @@ pub enum Destination {
     ///
     /// The `Address` variant contains the address to which the transaction is directed.
     Address(Address),
+    /// Represents multiple recipients batched into a single transaction.
+    ///
+    /// Each entry is an independent `(Address, SendAmount)` pair, letting callers emit one
+    /// `TxOut` per payee instead of paying for a separate transaction (and separate fees/UTXO
+    /// footprint) per recipient. At most one entry may use [`SendAmount::Max`]; that recipient
+    /// absorbs whatever remains after the other recipients and the fee are paid. The outer
+    /// `send_amount` argument to [`Wallet::spend_coins`] is ignored when this variant is used,
+    /// since each recipient already carries its own amount.
+    Multi(Vec<(Address, SendAmount)>),
 }
 
 impl FromStr for Destination {
@@ -66,6 +84,22 @@ impl FromStr for Destination {
 }
 
 impl Wallet {
+    /// Checks `address` is valid for `self.store.network`, allowing for the fact that testnet
+    /// and signet addresses share the same vbyte and so can't be told apart from the address
+    /// alone.
+    fn check_address_network(&self, address: &Address) -> Result<(), WalletError> {
+        let address = address.as_unchecked();
+        let testnet_signet_type = (address.is_valid_for_network(Network::Testnet)
+            || address.is_valid_for_network(Network::Signet))
+            && (self.store.network == Network::Testnet || self.store.network == Network::Signet);
+        if !address.is_valid_for_network(self.store.network) && !testnet_signet_type {
+            return Err(WalletError::General(
+                "Wrong address type in destinations.".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
     /// API to perform spending from wallet UTXOs, including descriptor coins and swap coins.
     ///
     /// The caller needs to specify a list of UTXO data and their corresponding `spend_info`.
@@ -84,13 +118,19 @@ impl Wallet {
     ///   value to the specified destination.
     /// - If [SendAmount::Amount] is used, a custom value is sent, and any remaining funds
     ///    are held in a change address, if applicable.
+    /// - If [Destination::Multi] is used, one output is created per recipient and `send_amount`
+    ///   is ignored; at most one recipient may use [SendAmount::Max] to absorb the remainder.
     ///
+    /// `rbf` signals BIP125 opt-in replace-by-fee on every input (sequence `0xFFFFFFFD`) so the
+    /// transaction can later be fee-bumped with [`Wallet::bump_fee`]; pass `false` for a
+    /// non-replaceable send.
     pub fn spend_from_wallet(
         &mut self,
         fee_rate: f64,
         send_amount: SendAmount,
         destination: Destination,
         coins_to_spend: &[(ListUnspentResultEntry, UTXOSpendInfo)],
+        rbf: bool,
     ) -> Result<Transaction, WalletError> {
         let coins = coins_to_spend
             .iter()
@@ -101,16 +141,71 @@ impl Wallet {
                 )
             })
             .collect::<Vec<_>>();
-        self.spend_coins(fee_rate, send_amount, destination, &coins)
+        self.spend_coins(fee_rate, send_amount, destination, &coins, rbf, false)
     }
 
+    /// `force_all_inputs` skips Branch-and-Bound coin selection and spends every UTXO in
+    /// `coins_to_spend` as-is. [`Wallet::bump_fee`] needs this: a BIP125 replacement must spend
+    /// the same (or a superset of the) inputs as the transaction it replaces, so it can't let
+    /// selection prune down to a subset.
     pub fn spend_coins(
         &mut self,
         fee_rate: f64,
         send_amount: SendAmount,
         destination: Destination,
         coins_to_spend: &Vec<&(ListUnspentResultEntry, UTXOSpendInfo)>,
+        rbf: bool,
+        force_all_inputs: bool,
     ) -> Result<Transaction, WalletError> {
+        let (mut tx, vsize, valid_coins) = self.build_unsigned_tx(
+            fee_rate,
+            send_amount,
+            destination,
+            coins_to_spend,
+            rbf,
+            force_all_inputs,
+        )?;
+
+        self.sign_transaction(
+            &mut tx,
+            &mut valid_coins.iter().map(|(_, usi)| (*usi).clone()),
+        )?;
+
+        let signed_tx_vsize = tx.vsize();
+        assert_eq!(
+            signed_tx_vsize, vsize,
+            "Calculated vsize {} didn't match signed tx vsize {}",
+            signed_tx_vsize, vsize
+        );
+
+        log::debug!("Signed Transaction : {:?}", tx.raw_hex());
+        Ok(tx)
+    }
+
+    /// Builds the unsigned transaction and coin selection shared by `spend_coins` and
+    /// `build_psbt`: filters and selects the eligible UTXOs, resolves the destination into
+    /// concrete outputs, and adds a change output where applicable. Returns the unsigned
+    /// transaction, its expected vsize, and the UTXOs selected as inputs (in the same order as
+    /// `tx.input`).
+    ///
+    /// `force_all_inputs` skips coin selection and spends every eligible UTXO in
+    /// `coins_to_spend`; see [`Wallet::spend_coins`].
+    pub(super) fn build_unsigned_tx<'c>(
+        &mut self,
+        fee_rate: f64,
+        send_amount: SendAmount,
+        destination: Destination,
+        coins_to_spend: &'c Vec<&'c (ListUnspentResultEntry, UTXOSpendInfo)>,
+        rbf: bool,
+        force_all_inputs: bool,
+    ) -> Result<
+        (
+            Transaction,
+            usize,
+            Vec<(&'c ListUnspentResultEntry, &'c UTXOSpendInfo)>,
+        ),
+        WalletError,
+    > {
         log::info!("Creating Direct-Spend from Wallet.");
 
         // Set the Anti-Fee-Snipping locktime
@@ -124,90 +219,151 @@ impl Wallet {
             output: vec![],
         };
 
-        let mut total_input_value = Amount::ZERO;
-        let mut total_witness_size = 0;
         let mut valid_coins = Vec::new();
 
         for (utxo_data, spend_info) in coins_to_spend {
             match spend_info {
-                UTXOSpendInfo::SeedCoin { .. } => {
-                    total_witness_size += P2PWPKH_WITNESS_SIZE;
-                    valid_coins.push((utxo_data, spend_info));
-                    total_input_value += utxo_data.amount;
-                }
-                UTXOSpendInfo::SwapCoin { .. } => {
-                    total_witness_size += P2WSH_MULTISIG_2OF2_WITNESS_SIZE;
-                    valid_coins.push((utxo_data, spend_info));
-                    total_input_value += utxo_data.amount;
+                UTXOSpendInfo::SeedCoin { .. } | UTXOSpendInfo::SwapCoin { .. } => {
+                    valid_coins.push((*utxo_data, *spend_info));
                 }
                 UTXOSpendInfo::FidelityBondCoin { .. }
                 | UTXOSpendInfo::HashlockContract { .. }
                 | UTXOSpendInfo::TimelockContract { .. } => {
+                    // `force_all_inputs` callers (namely `Wallet::bump_fee`) need every input in
+                    // `coins_to_spend` preserved verbatim; silently dropping one here would build
+                    // a replacement that's missing an input instead of erroring clearly.
+                    if force_all_inputs {
+                        return Err(WalletError::General(format!(
+                            "Cannot force-spend {:?}: Fidelity Bond and contract UTXOs aren't \
+                             supported by this spend path.",
+                            spend_info
+                        )));
+                    }
                     log::warn!("Skipping Fidelity Bond or Contract UTXO: {:?}", spend_info);
-                    continue;
                 }
             }
         }
 
+        // Resolve the destination into one or more (address, amount) recipients. A single
+        // `Wallet`/`Address` destination is just a batch of one; `Multi` lets the caller emit
+        // several `TxOut`s in one transaction.
+        let recipients: Vec<(Address, SendAmount)> = match destination {
+            Destination::Wallet => {
+                let addr = self.get_next_internal_addresses(1)?[0].clone();
+                vec![(addr, send_amount)]
+            }
+            Destination::Address(a) => vec![(a, send_amount)],
+            Destination::Multi(recipients) => recipients,
+        };
+
+        if recipients.is_empty() {
+            return Err(WalletError::General(
+                "No recipients specified for spend.".to_string(),
+            ));
+        }
+
+        let max_count = recipients
+            .iter()
+            .filter(|(_, amount)| matches!(amount, SendAmount::Max))
+            .count();
+        if max_count > 1 {
+            return Err(WalletError::General(
+                "At most one recipient may use SendAmount::Max.".to_string(),
+            ));
+        }
+        let has_max = max_count == 1;
+
+        let mut fixed_total = Amount::ZERO;
+        for (address, amount) in &recipients {
+            self.check_address_network(address)?;
+            if let SendAmount::Amount(a) = amount {
+                fixed_total += *a;
+            }
+        }
+
+        // For a fixed total, pick a minimal subset of the eligible UTXOs via Branch-and-Bound
+        // instead of spending all of them (see `coin_select`). A `Max` recipient absorbs the
+        // remainder by definition, so there's nothing to select there.
+        if !has_max && !force_all_inputs {
+            // select_coins' target only has to be covered by the candidates' own effective
+            // value, so the fee for the skeleton and recipient outputs (nobody's "own" spend
+            // cost) has to be added in separately here.
+            let output_scripts: Vec<ScriptBuf> =
+                recipients.iter().map(|(address, _)| address.script_pubkey()).collect();
+            let target = fixed_total + coin_select::non_input_fee(&output_scripts, fee_rate);
+            valid_coins = coin_select::select_coins(&valid_coins, target, fee_rate);
+        }
+
+        let mut total_input_value = Amount::ZERO;
+        let mut total_witness_size = 0;
+        for (utxo_data, spend_info) in &valid_coins {
+            total_input_value += utxo_data.amount;
+            total_witness_size += match spend_info {
+                UTXOSpendInfo::SeedCoin { .. } => P2PWPKH_WITNESS_SIZE,
+                UTXOSpendInfo::SwapCoin { .. } => P2WSH_MULTISIG_2OF2_WITNESS_SIZE,
+                UTXOSpendInfo::FidelityBondCoin { .. }
+                | UTXOSpendInfo::HashlockContract { .. }
+                | UTXOSpendInfo::TimelockContract { .. } => {
+                    unreachable!("fidelity bond and contract UTXOs are filtered out above")
+                }
+            };
+        }
+
+        // BIP125 opt-in RBF: any sequence below 0xFFFFFFFE signals replaceability, so the
+        // non-replaceable branch can't use `Sequence::ZERO` — it has to be exactly
+        // `ENABLE_LOCKTIME_NO_RBF` (0xFFFFFFFE), the only value that's both `>= 0xFFFFFFFE` (so it
+        // doesn't signal RBF) and `!= 0xFFFFFFFF` (so it still respects the anti-fee-snipping
+        // locktime set above).
+        let sequence = if rbf {
+            Sequence::ENABLE_RBF_NO_LOCKTIME
+        } else {
+            Sequence::ENABLE_LOCKTIME_NO_RBF
+        };
         for (utxo_data, _) in &valid_coins {
             tx.input.push(TxIn {
                 previous_output: OutPoint::new(utxo_data.txid, utxo_data.vout),
-                sequence: Sequence::ZERO,
+                sequence,
                 witness: Witness::new(),
                 script_sig: ScriptBuf::new(),
             });
         }
-        let dest_addr = match destination {
-            Destination::Wallet => self.get_next_internal_addresses(1)?[0].clone(),
-            Destination::Address(a) => {
-                //testnet and signet addresses have the same vbyte
-                //so a.network is always testnet even if the address is signet
-                let testnet_signet_type = (a.as_unchecked().is_valid_for_network(Network::Testnet)
-                    || a.as_unchecked().is_valid_for_network(Network::Signet))
-                    && (self.store.network == Network::Testnet
-                        || self.store.network == Network::Signet);
-                if !a.as_unchecked().is_valid_for_network(self.store.network)
-                    && !testnet_signet_type
-                {
-                    return Err(WalletError::General(
-                        "Wrong address type in destinations.".to_string(),
-                    ));
-                }
-                a
-            }
-        };
-
-        let txout = TxOut {
-            script_pubkey: dest_addr.script_pubkey(),
-            value: Amount::ZERO, //Temporary value
-        };
-
-        tx.output.push(txout);
+        // The `Max` recipient (if any) gets a placeholder output now and is topped up with
+        // whatever remains once the fee and every fixed amount are known.
+        let max_output_index = recipients
+            .iter()
+            .position(|(_, amount)| matches!(amount, SendAmount::Max));
+
+        for (address, amount) in &recipients {
+            tx.output.push(TxOut {
+                script_pubkey: address.script_pubkey(),
+                value: match amount {
+                    SendAmount::Amount(a) => *a,
+                    SendAmount::Max => Amount::ZERO, // filled in below
+                },
+            });
+        }
 
-        let base_size = tx.base_size();
-        let vsize = (base_size * 4 + total_witness_size) / 4;
-        let fee = Amount::from_sat((fee_rate * vsize as f64).ceil() as u64);
+        let (mut vsize, fee) = estimate_fee(tx.base_size(), total_witness_size, fee_rate);
         log::info!("Total Input Amount: {} | Fees: {}", total_input_value, fee);
 
-        if let SendAmount::Amount(a) = send_amount {
-            if a + fee > total_input_value {
-                return Err(WalletError::InsufficientFund {
-                    available: total_input_value.to_btc(),
-                    required: (a + fee).to_btc(),
-                });
-            }
+        if fixed_total + fee > total_input_value {
+            return Err(WalletError::InsufficientFund {
+                available: total_input_value.to_btc(),
+                required: (fixed_total + fee).to_btc(),
+            });
         }
 
-        let value = match send_amount {
-            SendAmount::Max => total_input_value - fee,
-            SendAmount::Amount(a) => a,
-        };
+        if let Some(idx) = max_output_index {
+            tx.output[idx].value = total_input_value - fee - fixed_total;
+        }
 
-        tx.output[0].value = value;
-        log::info!("Sending {} to {}", value, dest_addr);
+        for ((address, _), txout) in recipients.iter().zip(tx.output.iter()) {
+            log::info!("Sending {} to {}", txout.value, address);
+        }
 
-        // Only include change if remaining > dust
-        if let SendAmount::Amount(amount) = send_amount {
+        // Only include change if remaining > dust, and only when nothing already absorbs the
+        // remainder (i.e. no recipient used `SendAmount::Max`).
+        if max_output_index.is_none() {
             let internal_spk = self.get_next_internal_addresses(1)?[0].script_pubkey();
             let minimal_nondust = internal_spk.minimal_non_dust();
 
@@ -217,11 +373,10 @@ impl Wallet {
                 script_pubkey: internal_spk.clone(),
             });
 
-            let base_wchange = tx_wchange.base_size();
-            let vsize_wchange = (base_wchange * 4 + total_witness_size) / 4;
-            let fee_wchange = Amount::from_sat((fee_rate * vsize_wchange as f64).ceil() as u64);
+            let (vsize_wchange, fee_wchange) =
+                estimate_fee(tx_wchange.base_size(), total_witness_size, fee_rate);
 
-            let remaining_wchange = total_input_value - amount - fee_wchange;
+            let remaining_wchange = total_input_value - fixed_total - fee_wchange;
 
             if remaining_wchange > minimal_nondust {
                 log::info!("Adding Change {}: {}", internal_spk, remaining_wchange);
@@ -229,6 +384,7 @@ impl Wallet {
                     script_pubkey: internal_spk,
                     value: remaining_wchange,
                 });
+                vsize = vsize_wchange;
                 log::info!(
                     "Adding change output with {} sats (fee: {})",
                     remaining_wchange,
@@ -242,20 +398,7 @@ impl Wallet {
             }
         }
 
-        self.sign_transaction(
-            &mut tx,
-            &mut coins_to_spend.iter().map(|(_, usi)| usi.clone()),
-        )?;
-
-        let signed_tx_vsize = tx.vsize();
-        assert_eq!(
-            signed_tx_vsize, vsize,
-            "Calculated vsize {} didn't match signed tx vsize {}",
-            signed_tx_vsize, vsize
-        );
-
-        log::debug!("Signed Transaction : {:?}", tx.raw_hex());
-        Ok(tx)
+        Ok((tx, vsize, valid_coins))
     }
 }
 
@@ -0,0 +1,129 @@
+//! Fee bumping for previously broadcast wallet transactions: RBF replacement and CPFP.
+//!
+//! [`Wallet::bump_fee`] covers the direct case: a transaction built with `rbf: true` that's
+//! still unconfirmed. [`Wallet::cpfp`] covers the other one — a parent that didn't opt into RBF
+//! (or isn't ours to replace) — by spending one of its unconfirmed outputs with a high-fee child.
+
+use bitcoin::{Address, Amount, OutPoint, Transaction, Txid};
+use bitcoind::bitcoincore_rpc::{json::ListUnspentResultEntry, RpcApi};
+
+use super::{
+    api::UTXOSpendInfo,
+    direct_send::{Destination, SendAmount},
+    error::WalletError,
+    Wallet,
+};
+
+impl Wallet {
+    /// Whether `tx` opted into BIP125 replace-by-fee: at least one input has a sequence number
+    /// below `0xFFFFFFFE`.
+    fn signals_rbf(tx: &Transaction) -> bool {
+        tx.input.iter().any(|input| input.sequence.0 < 0xFFFFFFFE)
+    }
+
+    /// Rebuilds a previously broadcast, still-unconfirmed `txid` at a higher feerate and returns
+    /// the replacement.
+    ///
+    /// `txid` must have signaled RBF (built via [`Wallet::spend_coins`] with `rbf: true`) and
+    /// must still be one of this wallet's transactions. BIP125 requires the replacement to pay a
+    /// strictly higher absolute fee and feerate than the original, and to spend the same (or a
+    /// superset of the) inputs; keeping exactly the same inputs and only raising the feerate
+    /// satisfies both.
+    pub fn bump_fee(&mut self, txid: Txid, new_fee_rate: f64) -> Result<Transaction, WalletError> {
+        let old_tx: Transaction = self.rpc.get_raw_transaction(&txid, None)?;
+
+        if !Self::signals_rbf(&old_tx) {
+            return Err(WalletError::General(
+                "Transaction did not signal replace-by-fee.".to_string(),
+            ));
+        }
+
+        let old_fee = self.rpc.get_mempool_entry(&txid)?.fees.base;
+        let old_fee_rate = old_fee.to_sat() as f64 / old_tx.vsize() as f64;
+        if new_fee_rate <= old_fee_rate {
+            return Err(WalletError::General(
+                "Replacement feerate must exceed the original transaction's feerate.".to_string(),
+            ));
+        }
+
+        let coins: Vec<(ListUnspentResultEntry, UTXOSpendInfo)> = old_tx
+            .input
+            .iter()
+            .map(|input| {
+                self.get_utxo_spend_info(&input.previous_output)
+                    .ok_or_else(|| {
+                        WalletError::General(format!(
+                            "Don't know how to spend input {}, can't rebuild the replacement.",
+                            input.previous_output
+                        ))
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+        let coins_to_spend = coins.iter().collect::<Vec<_>>();
+
+        // Every output that isn't one of our own addresses is a real recipient whose payment
+        // must be preserved; our own output was the original transaction's change and is dropped
+        // so `spend_coins` can recompute it at the new, higher fee.
+        let mut recipients = Vec::new();
+        for output in &old_tx.output {
+            let address = Address::from_script(&output.script_pubkey, self.store.network)
+                .map_err(|e| WalletError::General(format!("Unrecognized output script: {e}")))?;
+            if self.rpc.get_address_info(&address)?.is_mine == Some(true) {
+                continue;
+            }
+            recipients.push((address, SendAmount::Amount(output.value)));
+        }
+
+        let replacement = self.spend_coins(
+            new_fee_rate,
+            SendAmount::Max, // unused: Destination::Multi carries each recipient's own amount
+            Destination::Multi(recipients),
+            &coins_to_spend,
+            true,
+            true, // force_all_inputs: must spend the same (or a superset of the) inputs
+        )?;
+
+        // BIP125 rule 3: the replacement must pay a strictly higher absolute fee, not just a
+        // higher feerate (a smaller replacement could otherwise pay less in total).
+        let total_input_value = coins
+            .iter()
+            .fold(Amount::ZERO, |acc, (utxo, _)| acc + utxo.amount);
+        let total_output_value = replacement
+            .output
+            .iter()
+            .fold(Amount::ZERO, |acc, txout| acc + txout.value);
+        let new_fee = total_input_value - total_output_value;
+        if new_fee <= old_fee {
+            return Err(WalletError::General(
+                "Replacement must pay a higher absolute fee than the original transaction."
+                    .to_string(),
+            ));
+        }
+
+        Ok(replacement)
+    }
+
+    /// Spends an unconfirmed wallet output `parent_output` with a high-fee child, for when its
+    /// parent transaction didn't opt into RBF and so can't be replaced directly.
+    pub fn cpfp(
+        &mut self,
+        parent_output: OutPoint,
+        child_fee_rate: f64,
+    ) -> Result<Transaction, WalletError> {
+        let utxo = self.get_utxo_spend_info(&parent_output).ok_or_else(|| {
+            WalletError::General(format!(
+                "{} is not a known wallet UTXO, can't CPFP it.",
+                parent_output
+            ))
+        })?;
+
+        self.spend_coins(
+            child_fee_rate,
+            SendAmount::Max,
+            Destination::Wallet,
+            &vec![&utxo],
+            true,
+            false,
+        )
+    }
+}
@@ -1,10 +1,14 @@
 //! The Coinswap Wallet (unsecured). Used by both the Taker and Maker.
 
 mod api;
+mod coin_select;
+mod deposit;
 mod direct_send;
 mod error;
+mod fee_bump;
 mod fidelity;
 mod funding;
+mod psbt;
 mod rpc;
 mod storage;
 mod swapcoin;
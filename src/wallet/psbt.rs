@@ -0,0 +1,112 @@
+//! Unsigned PSBT construction for external and hardware signing.
+//!
+//! [`Wallet::build_psbt`] runs the same input-selection and change logic as `spend_coins`
+//! (via `build_unsigned_tx`) but stops short of signing. Each input is populated with its
+//! `witness_utxo`, the 2-of-2 `witness_script` for `SwapCoin` inputs, and BIP32 derivation
+//! metadata for seed coins, so that a watch-only or offline signer can finalize it.
+
+use std::str::FromStr;
+
+use bitcoin::{
+    bip32::DerivationPath,
+    psbt::{Psbt, PsbtSighashType},
+    secp256k1::Secp256k1,
+    Transaction, TxOut,
+};
+use bitcoind::bitcoincore_rpc::json::ListUnspentResultEntry;
+
+use crate::wallet::api::UTXOSpendInfo;
+
+use super::{
+    direct_send::{Destination, SendAmount},
+    error::WalletError,
+    Wallet,
+};
+
+impl Wallet {
+    /// Builds an unsigned PSBT for the same spend [`Wallet::spend_coins`] would produce, for
+    /// handoff to a watch-only or offline/hardware signer.
+    ///
+    /// Contract (Hashlock/Timelock) and Fidelity Bond UTXOs are skipped here exactly as they are
+    /// in `spend_coins`; this API only covers the seed and swap coin spend path.
+    pub fn build_psbt(
+        &mut self,
+        fee_rate: f64,
+        send_amount: SendAmount,
+        destination: Destination,
+        coins_to_spend: &Vec<&(ListUnspentResultEntry, UTXOSpendInfo)>,
+        rbf: bool,
+        force_all_inputs: bool,
+    ) -> Result<Psbt, WalletError> {
+        let (tx, _vsize, selected_coins) = self.build_unsigned_tx(
+            fee_rate,
+            send_amount,
+            destination,
+            coins_to_spend,
+            rbf,
+            force_all_inputs,
+        )?;
+
+        let mut psbt = Psbt::from_unsigned_tx(tx)
+            .map_err(|e| WalletError::General(format!("Failed to build PSBT: {e}")))?;
+
+        let master_fingerprint = self.store.master_key.fingerprint(&Secp256k1::new());
+
+        for (psbt_input, (utxo_data, spend_info)) in psbt.inputs.iter_mut().zip(&selected_coins) {
+            psbt_input.witness_utxo = Some(TxOut {
+                value: utxo_data.amount,
+                script_pubkey: utxo_data.script_pub_key.clone(),
+            });
+            psbt_input.sighash_type = Some(PsbtSighashType::from(bitcoin::EcdsaSighashType::All));
+
+            match spend_info {
+                UTXOSpendInfo::SeedCoin { path, .. } => {
+                    let derivation_path = DerivationPath::from_str(path).map_err(|_| {
+                        WalletError::General(format!("Invalid derivation path: {path}"))
+                    })?;
+                    let derived = self
+                        .store
+                        .master_key
+                        .derive_priv(&Secp256k1::new(), &derivation_path)
+                        .map_err(|e| WalletError::General(format!("Failed to derive key: {e}")))?;
+                    let pubkey = derived.private_key.public_key(&Secp256k1::new());
+                    psbt_input
+                        .bip32_derivation
+                        .insert(pubkey, (master_fingerprint, derivation_path));
+                }
+                UTXOSpendInfo::SwapCoin {
+                    multisig_redeemscript,
+                    ..
+                } => {
+                    psbt_input.witness_script = Some(multisig_redeemscript.clone());
+                }
+                UTXOSpendInfo::FidelityBondCoin { .. }
+                | UTXOSpendInfo::HashlockContract { .. }
+                | UTXOSpendInfo::TimelockContract { .. } => {
+                    unreachable!("contract and fidelity UTXOs are filtered out by build_unsigned_tx")
+                }
+            }
+        }
+
+        Ok(psbt)
+    }
+
+    /// Signs every input of `psbt` that this wallet holds a seed-coin key for, deriving each
+    /// private key from its `bip32_derivation` entry. `SwapCoin` inputs need the counterparty's
+    /// signature too and are left for the caller to complete.
+    pub fn sign_psbt(&self, psbt: &mut Psbt) -> Result<(), WalletError> {
+        let secp = Secp256k1::new();
+        psbt.sign(&self.store.master_key, &secp)
+            .map_err(|(_, errors)| WalletError::General(format!("Failed to sign PSBT: {errors:?}")))?;
+        Ok(())
+    }
+
+    /// Extracts the [`Transaction`] from `psbt`, taking each input's witness from its
+    /// `final_script_witness`. Partial signatures (such as those added by
+    /// [`Wallet::sign_psbt`]) are not assembled into final witnesses here; an input without a
+    /// `final_script_witness` comes out with an empty witness.
+    pub fn finalize_psbt(psbt: Psbt) -> Result<Transaction, WalletError> {
+        psbt.extract_tx()
+            .map_err(|e| WalletError::General(format!("Failed to finalize PSBT: {e}")))
+    }
+}
@@ -1,18 +1,35 @@
-use super::{Destination, SendAmount, UTXOSpendInfo};
-use crate::wallet::WalletError;
-use bitcoin::Transaction;
+//! A unified spend subsystem: one `TransactionBuilder` per UTXO kind, sharing fee and change
+//! logic while specializing input sizing and signing.
+//!
+//! - [`BasicTxBuilder`] spends seed and swap coins — the regular wallet spend path, and just
+//!   delegates to [`Wallet::spend_coins`]/[`Wallet::build_psbt`], which already implement it.
+//! - [`FidelityTxBuilder`] spends a matured `FidelityBondCoin` back into the wallet once its
+//!   locktime has passed.
+//! - [`ContractTxBuilder`] spends the two contract UTXO kinds a swap can leave behind:
+//!   a `HashlockContract` (redeemed with the preimage once we're the coin's recipient) or a
+//!   `TimelockContract` (refunded once its locktime has passed and we're the coin's sender).
+
+use bitcoin::{
+    absolute::LockTime, psbt::Psbt, transaction::Version, Amount, OutPoint, ScriptBuf, Sequence,
+    Transaction, TxIn, TxOut, Witness,
+};
 use bitcoind::bitcoincore_rpc::json::ListUnspentResultEntry;
 
-pub trait TransactionBuilder {
-    fn build_tx(&self, params: BuildTxParams) -> Result<Transaction, WalletError>;
-    fn sign_tx(&self, tx: &mut Transaction, inputs: &[TxInput]) -> Result<(), WalletError>;
-}
+use super::{
+    direct_send::estimate_fee, fidelity_redeemscript, Destination, SendAmount, UTXOSpendInfo,
+    Wallet,
+};
+use crate::wallet::WalletError;
 
+/// Shared parameters for building a spend: which UTXOs to spend, where to send them, and at
+/// what feerate. Every `TransactionBuilder` impl takes the same params; they differ only in
+/// which UTXO kinds they accept and how they size and sign their inputs.
 pub struct BuildTxParams {
     pub fee_rate: f64,
     pub amount: SendAmount,
     pub destination: Destination,
     pub coins_to_spend: Vec<(ListUnspentResultEntry, UTXOSpendInfo)>,
+    pub rbf: bool,
 }
 
 pub struct TxInput {
@@ -20,26 +37,273 @@ pub struct TxInput {
     pub spend_info: UTXOSpendInfo,
 }
 
+pub trait TransactionBuilder {
+    /// Builds and signs a complete transaction spending `params.coins_to_spend`.
+    fn build_tx(&self, wallet: &mut Wallet, params: BuildTxParams) -> Result<Transaction, WalletError>;
+    /// Same input-selection and change logic as `build_tx`, but stops at an unsigned PSBT for
+    /// external or hardware signing instead of producing a signed `Transaction`.
+    fn build_psbt(&self, wallet: &mut Wallet, params: BuildTxParams) -> Result<Psbt, WalletError>;
+}
+
+/// Spends seed and swap coins — the regular wallet spend path already implemented by
+/// `Wallet::spend_coins`/`Wallet::build_psbt`.
 pub struct BasicTxBuilder;
-pub struct FidelityTxBuilder;
-pub struct ContractTxBuilder;
 
 impl TransactionBuilder for BasicTxBuilder {
-    fn build_tx() -> Result<Transaction, WalletError> {
-        todo!()
+    fn build_tx(&self, wallet: &mut Wallet, params: BuildTxParams) -> Result<Transaction, WalletError> {
+        let coins_to_spend = params.coins_to_spend.iter().collect::<Vec<_>>();
+        wallet.spend_coins(
+            params.fee_rate,
+            params.amount,
+            params.destination,
+            &coins_to_spend,
+            params.rbf,
+            false,
+        )
     }
 
-    fn sign_tx(&self, tx: &mut Transaction, inputs: &[TxInput]) -> Result<(), WalletError> {
-        todo!()
+    fn build_psbt(&self, wallet: &mut Wallet, params: BuildTxParams) -> Result<Psbt, WalletError> {
+        let coins_to_spend = params.coins_to_spend.iter().collect::<Vec<_>>();
+        wallet.build_psbt(
+            params.fee_rate,
+            params.amount,
+            params.destination,
+            &coins_to_spend,
+            params.rbf,
+            false,
+        )
     }
 }
 
+/// Spends a matured `FidelityBondCoin` back into the wallet.
+pub struct FidelityTxBuilder;
+
 impl TransactionBuilder for FidelityTxBuilder {
-    fn build_tx(&self, params: BuildTx) -> Result<Transaction, WalletError> {
-        todo!()
+    fn build_tx(&self, wallet: &mut Wallet, params: BuildTxParams) -> Result<Transaction, WalletError> {
+        let input = single_tx_input(&params)?;
+        let index = fidelity_bond_index(&input)?;
+        let utxo = &input.utxo;
+        let bond = wallet.get_fidelity_bond(index)?;
+        let redeemscript = fidelity_redeemscript(bond.locktime, &bond.pubkey);
+
+        let dest_spk = destination_script_pubkey(wallet, &params.destination)?;
+        let (mut tx, fee) = single_input_spend(
+            OutPoint::new(utxo.txid, utxo.vout),
+            Sequence::ENABLE_LOCKTIME_NO_RBF,
+            bond.locktime,
+            utxo.amount,
+            single_sig_witness_size(&redeemscript),
+            dest_spk,
+            params.fee_rate,
+        )?;
+        log::info!("Spending matured Fidelity Bond {}: fee {}", utxo.txid, fee);
+
+        wallet.sign_fidelity_spend(&mut tx, 0, bond, &redeemscript)?;
+        Ok(tx)
     }
 
-    fn sign_tx(&self, tx: &mut Transaction, inputs: &[TxInput]) -> Result<(), WalletError> {
-        todo!()
+    fn build_psbt(&self, wallet: &mut Wallet, params: BuildTxParams) -> Result<Psbt, WalletError> {
+        let input = single_tx_input(&params)?;
+        let index = fidelity_bond_index(&input)?;
+        let utxo = &input.utxo;
+        let bond = wallet.get_fidelity_bond(index)?;
+        let redeemscript = fidelity_redeemscript(bond.locktime, &bond.pubkey);
+
+        let dest_spk = destination_script_pubkey(wallet, &params.destination)?;
+        let (tx, _fee) = single_input_spend(
+            OutPoint::new(utxo.txid, utxo.vout),
+            Sequence::ENABLE_LOCKTIME_NO_RBF,
+            bond.locktime,
+            utxo.amount,
+            single_sig_witness_size(&redeemscript),
+            dest_spk,
+            params.fee_rate,
+        )?;
+
+        let mut psbt =
+            Psbt::from_unsigned_tx(tx).map_err(|e| WalletError::General(format!("Failed to build PSBT: {e}")))?;
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: utxo.amount,
+            script_pubkey: utxo.script_pub_key.clone(),
+        });
+        psbt.inputs[0].witness_script = Some(redeemscript);
+        Ok(psbt)
     }
 }
+
+/// Spends the two contract UTXO kinds a swap can leave behind: a `HashlockContract` (redeemed
+/// with the preimage) or a `TimelockContract` (refunded after its locktime).
+pub struct ContractTxBuilder;
+
+impl TransactionBuilder for ContractTxBuilder {
+    fn build_tx(&self, wallet: &mut Wallet, params: BuildTxParams) -> Result<Transaction, WalletError> {
+        let input = single_tx_input(&params)?;
+        let (utxo, spend_info) = (&input.utxo, &input.spend_info);
+        let outpoint = OutPoint::new(utxo.txid, utxo.vout);
+        let dest_spk = destination_script_pubkey(wallet, &params.destination)?;
+
+        let mut tx = match spend_info {
+            UTXOSpendInfo::HashlockContract { .. } => {
+                let swapcoin = wallet.find_incoming_swapcoin(&outpoint).ok_or_else(|| {
+                    WalletError::General(format!(
+                        "No incoming swapcoin known for hashlock contract {outpoint}"
+                    ))
+                })?;
+                let redeemscript = swapcoin.get_contract_redeemscript();
+                let (tx, fee) = single_input_spend(
+                    outpoint,
+                    Sequence::ZERO,
+                    LockTime::ZERO,
+                    utxo.amount,
+                    hashlock_witness_size(&redeemscript),
+                    dest_spk,
+                    params.fee_rate,
+                )?;
+                log::info!("Redeeming hashlock contract {outpoint}: fee {fee}");
+                tx
+            }
+            UTXOSpendInfo::TimelockContract { .. } => {
+                let swapcoin = wallet.find_outgoing_swapcoin(&outpoint).ok_or_else(|| {
+                    WalletError::General(format!(
+                        "No outgoing swapcoin known for timelock contract {outpoint}"
+                    ))
+                })?;
+                let redeemscript = swapcoin.get_contract_redeemscript();
+                let locktime = LockTime::from_height(swapcoin.get_timelock() as u32)?;
+                let (tx, fee) = single_input_spend(
+                    outpoint,
+                    Sequence::ENABLE_LOCKTIME_NO_RBF,
+                    locktime,
+                    utxo.amount,
+                    single_sig_witness_size(&redeemscript),
+                    dest_spk,
+                    params.fee_rate,
+                )?;
+                log::info!("Refunding timelock contract {outpoint}: fee {fee}");
+                tx
+            }
+            _ => {
+                return Err(WalletError::General(
+                    "ContractTxBuilder can only spend a HashlockContract or TimelockContract."
+                        .to_string(),
+                ))
+            }
+        };
+
+        match spend_info {
+            UTXOSpendInfo::HashlockContract { .. } => {
+                let swapcoin = wallet
+                    .find_incoming_swapcoin(&outpoint)
+                    .expect("looked up above");
+                swapcoin.sign_hashlock_transaction_input(0, &mut tx, utxo.amount)?;
+            }
+            UTXOSpendInfo::TimelockContract { .. } => {
+                let swapcoin = wallet
+                    .find_outgoing_swapcoin(&outpoint)
+                    .expect("looked up above");
+                swapcoin.sign_timelock_transaction_input(0, &mut tx, utxo.amount)?;
+            }
+            _ => unreachable!("checked above"),
+        }
+
+        Ok(tx)
+    }
+
+    fn build_psbt(&self, _wallet: &mut Wallet, _params: BuildTxParams) -> Result<Psbt, WalletError> {
+        // Contract spends are only built signed, by `build_tx`, which signs with the wallet's
+        // own swapcoin keys; no unsigned PSBT is produced for them.
+        Err(WalletError::General(
+            "ContractTxBuilder does not build PSBTs; use build_tx to build and sign a contract \
+             spend."
+                .to_string(),
+        ))
+    }
+}
+
+/// Extracts the single UTXO `FidelityTxBuilder`/`ContractTxBuilder` spend, bundled with its
+/// spend info. Both builders are single-input, no-change builders, so they share this lookup
+/// instead of each re-deriving it from `params.coins_to_spend`. Only the first entry of
+/// `params.coins_to_spend` is used; any further entries are ignored.
+fn single_tx_input(params: &BuildTxParams) -> Result<TxInput, WalletError> {
+    let (utxo, spend_info) = params
+        .coins_to_spend
+        .first()
+        .ok_or_else(|| WalletError::General("No UTXO to spend.".to_string()))?;
+    Ok(TxInput {
+        utxo: utxo.clone(),
+        spend_info: spend_info.clone(),
+    })
+}
+
+fn fidelity_bond_index(input: &TxInput) -> Result<u32, WalletError> {
+    match &input.spend_info {
+        UTXOSpendInfo::FidelityBondCoin { index, .. } => Ok(*index),
+        _ => Err(WalletError::General(
+            "FidelityTxBuilder can only spend a FidelityBondCoin.".to_string(),
+        )),
+    }
+}
+
+fn destination_script_pubkey(
+    wallet: &mut Wallet,
+    destination: &Destination,
+) -> Result<ScriptBuf, WalletError> {
+    match destination {
+        Destination::Wallet => Ok(wallet.get_next_internal_addresses(1)?[0].script_pubkey()),
+        Destination::Address(a) => Ok(a.script_pubkey()),
+        Destination::Multi(_) => Err(WalletError::General(
+            "Fidelity and contract spends only support a single destination.".to_string(),
+        )),
+    }
+}
+
+/// Builds a single-input, single-output transaction spending `input_value` at `fee_rate`, given
+/// the size in bytes (weight units) its (not-yet-filled) witness will occupy. There's no change:
+/// Fidelity Bond and contract UTXOs are swept in full to `destination_spk`.
+fn single_input_spend(
+    previous_output: OutPoint,
+    sequence: Sequence,
+    lock_time: LockTime,
+    input_value: Amount,
+    witness_size: usize,
+    destination_spk: ScriptBuf,
+    fee_rate: f64,
+) -> Result<(Transaction, Amount), WalletError> {
+    let mut tx = Transaction {
+        version: Version::TWO,
+        lock_time,
+        input: vec![TxIn {
+            previous_output,
+            sequence,
+            witness: Witness::new(),
+            script_sig: ScriptBuf::new(),
+        }],
+        output: vec![TxOut {
+            script_pubkey: destination_spk,
+            value: Amount::ZERO, // filled in below
+        }],
+    };
+
+    let (_vsize, fee) = estimate_fee(tx.base_size(), witness_size, fee_rate);
+    if fee > input_value {
+        return Err(WalletError::InsufficientFund {
+            available: input_value.to_btc(),
+            required: fee.to_btc(),
+        });
+    }
+    tx.output[0].value = input_value - fee;
+
+    Ok((tx, fee))
+}
+
+/// Witness size in bytes (weight units) of a single-signature P2WSH spend:
+/// `[signature, redeemscript]`.
+fn single_sig_witness_size(redeemscript: &ScriptBuf) -> usize {
+    1 + 72 + 1 + redeemscript.len()
+}
+
+/// Witness size in bytes (weight units) of a hashlock-branch P2WSH spend:
+/// `[signature, preimage, redeemscript]`.
+fn hashlock_witness_size(redeemscript: &ScriptBuf) -> usize {
+    1 + 72 + 1 + 32 + 1 + redeemscript.len()
+}